@@ -20,17 +20,26 @@
 
 #[macro_use]
 extern crate clap;
+extern crate chrono;
+extern crate futures;
 extern crate redeye;
-extern crate serde_json;
 extern crate tokio;
 
+use chrono::{FixedOffset, NaiveDate};
 use clap::{App, Arg, ArgMatches};
-use redeye::input::StdinBufReader;
-use redeye::parser::{CombinedLogLineParser, CommonLogLineParser, LogLineParser};
+use futures::future::{self, Either};
+use redeye::input::{self, StdinBufReader};
+use redeye::output::{self, OutputEncoder};
+use redeye::parser::{
+    CombinedLogLineParser, CommonLogLineParser, Context, FormatLogLineParser, LogLineParser,
+};
+use redeye::stats::{SummaryStats, DEFAULT_TOP_N};
 use redeye::types::RedeyeError;
 use std::env;
 use std::io::BufRead;
 use std::process;
+use std::thread;
+use std::sync::{Arc, Mutex};
 use tokio::io::{lines, stdout};
 use tokio::prelude::*;
 
@@ -42,10 +51,12 @@ fn parse_cli_opts<'a>(args: Vec<String>) -> ArgMatches<'a> {
         .set_term_width(MAX_TERM_WIDTH)
         .about(
             "\nRedeye converts NCSA or Apache HTTPd style access to JSON understood \
-             by Logstash. Access log entries are read line by line from stdin, \
-             converted to Logstash JSON, and emitted on stdout. Currently \
-             Common and Combined access log formats are supported. For more \
-             information about these formats, see \n\n\
+             by Logstash. Access log entries are read line by line from one or \
+             more files, or from stdin if no files are given, converted to \
+             Logstash JSON, and emitted on stdout. Currently Common and \
+             Combined access log formats are supported, as well as custom \
+             Apache LogFormat specs. For more information about these \
+             formats, see \n\n\
              https://httpd.apache.org/docs/current/logs.html#accesslog",
         ).arg(
             Arg::with_name("common-format")
@@ -62,14 +73,83 @@ fn parse_cli_opts<'a>(args: Vec<String>) -> ArgMatches<'a> {
                     "Parse log entries assuming the Combined log format. Entries \
                      that don't match this format will be discarded and a warning \
                      will be printed to stderr.",
-                ).conflicts_with_all(&["common-format"]),
+                ).conflicts_with_all(&["common-format", "format"]),
+        ).arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .value_name("SPEC")
+                .help(
+                    "Parse log entries using a custom Apache LogFormat spec, e.g. \
+                     '%h %l %u %t \"%r\" %>s %b'. Entries that don't match this \
+                     format will be discarded and a warning will be printed to \
+                     stderr.",
+                ).conflicts_with_all(&["common-format", "combined-format"]),
+        ).arg(
+            Arg::with_name("follow")
+                .short("f")
+                .long("follow")
+                .help(
+                    "Keep reading each input file as it grows, like `tail -f`, \
+                     instead of exiting once end-of-file is reached. Has no \
+                     effect when reading from stdin or from a file that is \
+                     being decompressed.",
+                ),
+        ).arg(
+            Arg::with_name("output-format")
+                .long("output-format")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&["ndjson", "json", "msgpack"])
+                .default_value("ndjson")
+                .help(
+                    "Encoding for emitted events: compact newline-delimited JSON, \
+                     pretty-printed JSON, or length-prefixed binary MessagePack.",
+                ),
+        ).arg(
+            Arg::with_name("timezone")
+                .long("timezone")
+                .takes_value(true)
+                .value_name("OFFSET")
+                .help(
+                    "Timezone to assume for timestamps that don't carry their \
+                     own UTC offset, e.g. '+0500'. Defaults to UTC.",
+                ),
+        ).arg(
+            Arg::with_name("assume-date")
+                .long("assume-date")
+                .takes_value(true)
+                .value_name("DATE")
+                .help(
+                    "Date to assume for timestamps that don't carry their own \
+                     date, in YYYY-MM-DD format. Defaults to today.",
+                ),
+        ).arg(
+            Arg::with_name("summary")
+                .long("summary")
+                .help(
+                    "Instead of emitting JSON per line, accumulate frequency \
+                     statistics over the stream of entries and print a summary \
+                     report once input ends.",
+                ),
+        ).arg(
+            Arg::with_name("paths")
+                .value_name("FILE")
+                .multiple(true)
+                .help(
+                    "Log files to read. If none are given, entries are read from \
+                     stdin. Files with a .gz, .bz2, .xz, or .zst extension are \
+                     transparently decompressed.",
+                ),
         ).get_matches_from(args)
 }
 
 fn new_parser_task<R, W>(
     reader: R,
-    parser: Box<LogLineParser + Send + Sync>,
+    parser: Arc<LogLineParser + Send + Sync>,
+    encoder: Arc<OutputEncoder + Send + Sync>,
     mut writer: W,
+    stats: Option<Arc<Mutex<SummaryStats>>>,
 ) -> impl Future<Item = (), Error = ()>
 where
     R: AsyncRead + BufRead,
@@ -80,9 +160,14 @@ where
         .for_each(move |line| {
             let _ = parser
                 .parse(&line)
-                .and_then(|event| serde_json::to_string(&event).map_err(RedeyeError::from))
-                .and_then(|json| writeln!(writer, "{}", json).map_err(RedeyeError::from))
-                .map_err(handle_redeye_error);
+                .and_then(|event| {
+                    if let Some(ref stats) = stats {
+                        stats.lock().unwrap().record(&event);
+                        return Ok(());
+                    }
+
+                    encoder.encode(&event, &mut writer)
+                }).map_err(handle_redeye_error);
             Ok(())
         }).map_err(handle_redeye_error)
 }
@@ -94,27 +179,161 @@ fn handle_redeye_error(err: RedeyeError) {
         RedeyeError::SerializationError(e) => format!("Serialization error: {}", e),
         RedeyeError::TimestampParseError(e) => format!("Invalid timestamp: {}", e),
         RedeyeError::ParseError(e) => format!("Invalid log line: {}", e),
+        RedeyeError::EncodingError(e) => format!("Encoding error: {}", e),
     };
 
     eprintln!("redeye: WARNING: {}", display);
 }
 
+fn open_file_task(
+    path: &str,
+    follow: bool,
+    parser: Arc<LogLineParser + Send + Sync>,
+    encoder: Arc<OutputEncoder + Send + Sync>,
+    stats: Option<Arc<Mutex<SummaryStats>>>,
+) -> impl Future<Item = (), Error = ()> {
+    match input::open(path, follow) {
+        Ok(reader) => Either::A(new_parser_task(reader, parser, encoder, stdout(), stats)),
+        Err(e) => {
+            eprintln!("redeye: ERROR: Could not open '{}': {}", path, e);
+            Either::B(future::ok(()))
+        }
+    }
+}
+
+/// Parse a `--timezone` value like `+0500` or `-0330` into a `FixedOffset`.
+fn parse_timezone_offset(text: &str) -> Result<FixedOffset, String> {
+    if text.len() != 5 {
+        return Err(format!("expected an offset like '+0500', got '{}'", text));
+    }
+
+    let sign = match &text[0..1] {
+        "+" => 1,
+        "-" => -1,
+        _ => return Err(format!("expected a leading '+' or '-', got '{}'", text)),
+    };
+
+    let hours: i32 = text[1..3]
+        .parse()
+        .map_err(|_| format!("invalid hour component in '{}'", text))?;
+    let minutes: i32 = text[3..5]
+        .parse()
+        .map_err(|_| format!("invalid minute component in '{}'", text))?;
+
+    if hours > 23 || minutes > 59 {
+        return Err(format!("offset out of range in '{}'", text));
+    }
+
+    Ok(FixedOffset::east(sign * (hours * 3600 + minutes * 60)))
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     let matches = parse_cli_opts(args);
 
-    let parser: Box<LogLineParser + Send + Sync> = if matches.is_present("common-format") {
-        Box::new(CommonLogLineParser::new())
+    let mut context = Context::new();
+
+    if let Some(offset) = matches.value_of("timezone") {
+        match parse_timezone_offset(offset) {
+            Ok(offset) => context = context.with_default_timezone(offset),
+            Err(e) => {
+                eprintln!("redeye: ERROR: Invalid timezone '{}': {}", offset, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(date) = matches.value_of("assume-date") {
+        match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => context = context.with_assume_date(date),
+            Err(e) => {
+                eprintln!("redeye: ERROR: Invalid assume-date '{}': {}", date, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let parser: Arc<LogLineParser + Send + Sync> = if matches.is_present("common-format") {
+        Arc::new(CommonLogLineParser::new(context))
     } else if matches.is_present("combined-format") {
-        Box::new(CombinedLogLineParser::new())
+        Arc::new(CombinedLogLineParser::new(context))
+    } else if let Some(spec) = matches.value_of("format") {
+        match FormatLogLineParser::new(spec, context) {
+            Ok(parser) => Arc::new(parser),
+            Err(e) => {
+                eprintln!("redeye: ERROR: Invalid format spec: {}", e);
+                process::exit(1);
+            }
+        }
     } else {
         eprintln!("redeye: ERROR: Log input format must be specified");
         process::exit(1);
     };
 
-    let reader = StdinBufReader::default();
-    let writer = stdout();
-    let lines = new_parser_task(reader, parser, writer);
+    let follow = matches.is_present("follow");
+    let paths: Vec<String> = matches
+        .values_of("paths")
+        .map(|vals| vals.map(String::from).collect())
+        .unwrap_or_default();
+
+    let encoder: Arc<OutputEncoder + Send + Sync> = output::encoder_for(
+        matches
+            .value_of("output-format")
+            .expect("output-format has a default value"),
+    ).expect("output-format is restricted to known possible_values")
+        .into();
+
+    let stats = if matches.is_present("summary") {
+        Some(Arc::new(Mutex::new(SummaryStats::new())))
+    } else {
+        None
+    };
+
+    if paths.is_empty() {
+        let reader = StdinBufReader::default();
+        let writer = stdout();
+        tokio::run(new_parser_task(
+            reader,
+            parser,
+            Arc::clone(&encoder),
+            writer,
+            stats.clone(),
+        ));
+    } else if follow {
+        // `FileBufReader` blocks its thread while polling a followed file
+        // for new data (see its `wait_for_data`), so running every followed
+        // path as a task on one shared tokio runtime risks the small
+        // worker pool filling up with blocked files and starving the
+        // rest. Give each followed path its own thread and runtime
+        // instead, so one idle file can never stall another.
+        let handles: Vec<_> = paths
+            .into_iter()
+            .map(|path| {
+                let parser = Arc::clone(&parser);
+                let encoder = Arc::clone(&encoder);
+                let stats = stats.clone();
+
+                thread::spawn(move || {
+                    tokio::run(open_file_task(&path, true, parser, encoder, stats));
+                })
+            }).collect();
+
+        for handle in handles {
+            let _ = handle.join();
+        }
+    } else {
+        for path in paths {
+            tokio::run(open_file_task(
+                &path,
+                false,
+                Arc::clone(&parser),
+                Arc::clone(&encoder),
+                stats.clone(),
+            ));
+        }
+    }
 
-    tokio::run(lines);
+    if let Some(stats) = stats {
+        print!("{}", stats.lock().unwrap().render(DEFAULT_TOP_N));
+    }
 }
@@ -0,0 +1,333 @@
+//
+//
+//
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+
+/// How long to sleep between polls for appended data in follow mode.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A `BufRead` over the process's standard input, suitable for
+/// feeding into `new_parser_task`.
+#[derive(Debug)]
+pub struct StdinBufReader {
+    reader: io::BufReader<io::Stdin>,
+}
+
+impl Default for StdinBufReader {
+    fn default() -> Self {
+        Self {
+            reader: io::BufReader::new(io::stdin()),
+        }
+    }
+}
+
+impl Read for StdinBufReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for StdinBufReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl AsyncRead for StdinBufReader {}
+
+/// A `BufRead` over a single log file, suitable for feeding into
+/// `new_parser_task`. In follow mode, once EOF is reached the reader
+/// polls for appended bytes instead of ending the stream, and
+/// transparently reopens the file if it shrinks in the meantime
+/// (truncation or log rotation).
+pub struct FileBufReader {
+    path: PathBuf,
+    file: io::BufReader<File>,
+    follow: bool,
+    offset: u64,
+}
+
+impl FileBufReader {
+    pub fn open<P: AsRef<Path>>(path: P, follow: bool) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+
+        Ok(Self {
+            path,
+            file: io::BufReader::new(file),
+            follow,
+            offset: 0,
+        })
+    }
+
+    /// Reopen the file from the beginning if it has shrunk since we last
+    /// read from it, which indicates truncation or rotation rather than
+    /// an ordinary append.
+    fn reopen_if_rotated(&mut self) -> io::Result<()> {
+        let len = fs::metadata(&self.path)?.len();
+
+        if len < self.offset {
+            self.file = io::BufReader::new(File::open(&self.path)?);
+            self.offset = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Block until there is data available to read, reopening a rotated
+    /// file and, in follow mode, polling for appended bytes instead of
+    /// reporting EOF.
+    fn wait_for_data(&mut self) -> io::Result<()> {
+        loop {
+            self.reopen_if_rotated()?;
+
+            if !self.file.fill_buf()?.is_empty() || !self.follow {
+                return Ok(());
+            }
+
+            thread::sleep(FOLLOW_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Read for FileBufReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.wait_for_data()?;
+        let n = self.file.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl BufRead for FileBufReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.wait_for_data()?;
+        self.file.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.file.consume(amt);
+        self.offset += amt as u64;
+    }
+}
+
+impl AsyncRead for FileBufReader {}
+
+/// Either a plain file or a transparently-decompressed one, so callers
+/// don't need to know which was picked for a given path.
+pub enum FileInput {
+    Plain(FileBufReader),
+    Decompressed(DecompressingReader),
+}
+
+/// Open `path` for reading, transparently decompressing it first if its
+/// extension (`.gz`, `.bz2`, `.xz`, `.zst`) indicates a known compression
+/// format and a matching decompressor binary is on `PATH`. Falls back to
+/// reading the file as-is when no matching binary is available. `follow`
+/// is only honored for plain files; a decompressor is a finite pipe and
+/// can't be tailed.
+pub fn open<P: AsRef<Path>>(path: P, follow: bool) -> io::Result<FileInput> {
+    let path = path.as_ref();
+
+    if let Some((bin, args)) = decompressor_for(path) {
+        match DecompressingReader::spawn(bin, args, path) {
+            Ok(reader) => return Ok(FileInput::Decompressed(reader)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!(
+                    "redeye: WARNING: '{}' not found on PATH, reading '{}' without decompression",
+                    bin,
+                    path.display()
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    FileBufReader::open(path, follow).map(FileInput::Plain)
+}
+
+/// Map a compressed file extension to the external decompressor binary
+/// and arguments that will write the decompressed bytes to stdout.
+fn decompressor_for(path: &Path) -> Option<(&'static str, &'static [&'static str])> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Some(("gzip", &["-d", "-c"])),
+        Some("bz2") => Some(("bzip2", &["-d", "-c"])),
+        Some("xz") => Some(("xz", &["-d", "-c"])),
+        Some("zst") => Some(("zstd", &["-d", "-c"])),
+        _ => None,
+    }
+}
+
+impl Read for FileInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            FileInput::Plain(ref mut r) => r.read(buf),
+            FileInput::Decompressed(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+impl BufRead for FileInput {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match *self {
+            FileInput::Plain(ref mut r) => r.fill_buf(),
+            FileInput::Decompressed(ref mut r) => r.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match *self {
+            FileInput::Plain(ref mut r) => r.consume(amt),
+            FileInput::Decompressed(ref mut r) => r.consume(amt),
+        }
+    }
+}
+
+impl AsyncRead for FileInput {}
+
+/// A `BufRead` over the decompressed stdout of an external decompressor
+/// (`gzip -d -c`, `bzip2 -d -c`, `xz -d -c`, `zstd -d -c`) spawned against
+/// a compressed log file. The decompressor's stderr is drained on a
+/// background thread so that a decompressor writing a lot of diagnostics
+/// there can't deadlock us by filling its pipe while we're blocked
+/// reading its stdout.
+pub struct DecompressingReader {
+    child: Child,
+    reader: io::BufReader<ChildStdout>,
+}
+
+impl DecompressingReader {
+    fn spawn(bin: &str, args: &[&str], path: &Path) -> io::Result<Self> {
+        let mut child = Command::new(bin)
+            .args(args)
+            .arg(path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let stdout = child.stdout.take().expect("child spawned with piped stdout");
+        let stderr = child.stderr.take().expect("child spawned with piped stderr");
+        let bin = bin.to_string();
+
+        thread::spawn(move || {
+            let mut stderr = io::BufReader::new(stderr);
+            let mut line = String::new();
+
+            while stderr.read_line(&mut line).unwrap_or(0) > 0 {
+                eprint!("redeye: WARNING: {}: {}", bin, line);
+                line.clear();
+            }
+        });
+
+        Ok(Self {
+            child,
+            reader: io::BufReader::new(stdout),
+        })
+    }
+}
+
+impl Read for DecompressingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for DecompressingReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl AsyncRead for DecompressingReader {}
+
+impl Drop for DecompressingReader {
+    fn drop(&mut self) {
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileBufReader;
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::{BufRead, Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let mut path = env::temp_dir();
+        path.push(format!(
+            "redeye-input-test-{}-{}",
+            name,
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        path
+    }
+
+    #[test]
+    fn test_reopen_if_rotated_restarts_from_beginning() {
+        let path = temp_path("rotate");
+        fs::write(&path, b"before rotation\n").unwrap();
+
+        let mut reader = FileBufReader::open(&path, false).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "before rotation\n");
+
+        // Simulate log rotation: truncate and write shorter content.
+        fs::write(&path, b"after rotation\n").unwrap();
+        reader.reopen_if_rotated().unwrap();
+
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "after rotation\n");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_follow_detects_appended_data() {
+        let path = temp_path("append");
+        fs::write(&path, b"first line\n").unwrap();
+
+        let mut reader = FileBufReader::open(&path, true).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "first line\n");
+
+        let append_path = path.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open(&append_path)
+                .unwrap();
+            file.write_all(b"second line\n").unwrap();
+        });
+
+        let mut second = String::new();
+        reader.read_line(&mut second).unwrap();
+        assert_eq!(second, "second line\n");
+
+        fs::remove_file(&path).ok();
+    }
+}
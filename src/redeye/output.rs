@@ -0,0 +1,68 @@
+//
+//
+//
+
+use parser::LogEvent;
+use serde_json;
+use std::io::Write;
+use types::{RedeyeError, RedeyeResult};
+
+/// Writes a single `LogEvent` to a sink, each implementation choosing its
+/// own framing (newline-delimited, length-prefixed, ...). Every
+/// implementation serializes through `LogEvent`'s `Serialize` impl, so
+/// the Logstash field-name mapping lives in exactly one place regardless
+/// of output format.
+pub trait OutputEncoder {
+    fn encode(&self, event: &LogEvent, writer: &mut Write) -> RedeyeResult<()>;
+}
+
+/// Compact, newline-delimited JSON - one `LogEvent` per line. This is
+/// the original, and still default, output format.
+#[derive(Debug, Default)]
+pub struct NdjsonEncoder;
+
+impl OutputEncoder for NdjsonEncoder {
+    fn encode(&self, event: &LogEvent, writer: &mut Write) -> RedeyeResult<()> {
+        let json = serde_json::to_string(event).map_err(RedeyeError::from)?;
+        writeln!(writer, "{}", json).map_err(RedeyeError::from)
+    }
+}
+
+/// Pretty-printed JSON, one `LogEvent` per call.
+#[derive(Debug, Default)]
+pub struct JsonEncoder;
+
+impl OutputEncoder for JsonEncoder {
+    fn encode(&self, event: &LogEvent, writer: &mut Write) -> RedeyeResult<()> {
+        let json = serde_json::to_string_pretty(event).map_err(RedeyeError::from)?;
+        writeln!(writer, "{}", json).map_err(RedeyeError::from)
+    }
+}
+
+/// Binary MessagePack, length-prefixed (a big-endian `u32` byte count
+/// ahead of each record) so a downstream consumer can read a stream of
+/// records back out without a line-oriented delimiter.
+#[derive(Debug, Default)]
+pub struct MsgpackEncoder;
+
+impl OutputEncoder for MsgpackEncoder {
+    fn encode(&self, event: &LogEvent, writer: &mut Write) -> RedeyeResult<()> {
+        let bytes =
+            rmp_serde::to_vec(event).map_err(|e| RedeyeError::EncodingError(e.to_string()))?;
+
+        writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Parse a `--output-format` CLI value into the matching encoder.
+pub fn encoder_for(name: &str) -> Option<Box<OutputEncoder + Send + Sync>> {
+    match name {
+        "ndjson" => Some(Box::new(NdjsonEncoder)),
+        "json" => Some(Box::new(JsonEncoder)),
+        "msgpack" => Some(Box::new(MsgpackEncoder)),
+        _ => None,
+    }
+}
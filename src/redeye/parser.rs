@@ -2,8 +2,9 @@
 //
 //
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 use regex::{Captures, Regex};
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use std::collections::HashMap;
 use types::{RedeyeError, RedeyeResult};
 
@@ -13,6 +14,32 @@ pub trait LogLineParser {
     fn parse(&self, line: &str) -> RedeyeResult<LogEvent>;
 }
 
+/// State threaded through parsing to resolve timestamps that are
+/// ambiguous on their own: a default timezone to assume when a log's
+/// timestamp carries no offset, and a date to combine with a timestamp
+/// that carries no date of its own.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    default_timezone: Option<FixedOffset>,
+    assume_date: Option<NaiveDate>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_default_timezone(mut self, timezone: FixedOffset) -> Self {
+        self.default_timezone = Some(timezone);
+        self
+    }
+
+    pub fn with_assume_date(mut self, date: NaiveDate) -> Self {
+        self.assume_date = Some(date);
+        self
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum LogFieldValue {
     Timestamp(DateTime<FixedOffset>),
@@ -31,12 +58,63 @@ impl From<HashMap<String, LogFieldValue>> for LogEvent {
     }
 }
 
+// Each parser stores its captured fields under the Logstash field names
+// it wants emitted (e.g. "@timestamp", "request_url"), so serializing the
+// map as-is is all that's needed to produce Logstash-shaped output -
+// every output encoder shares this single mapping.
+impl Serialize for LogFieldValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            LogFieldValue::Timestamp(ref ts) => serializer.serialize_str(&ts.to_rfc3339()),
+            LogFieldValue::Text(ref s) => serializer.serialize_str(s),
+            LogFieldValue::Int(n) => serializer.serialize_u64(n),
+        }
+    }
+}
+
+impl Serialize for LogEvent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.values.len()))?;
+        for (key, value) in &self.values {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl LogEvent {
+    pub fn get(&self, name: &str) -> Option<&LogFieldValue> {
+        self.values.get(name)
+    }
+
+    pub fn get_text(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(LogFieldValue::Text(ref s)) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<u64> {
+        match self.values.get(name) {
+            Some(LogFieldValue::Int(n)) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
 pub struct CommonLogLineParser {
     regex: Regex,
+    context: Context,
 }
 
 impl CommonLogLineParser {
-    pub fn new() -> Self {
+    pub fn new(context: Context) -> Self {
         Self {
             regex: Regex::new(concat!(
                 r"^([^\s]+)\s+", // host
@@ -51,6 +129,7 @@ impl CommonLogLineParser {
                 r"([^\s]+)\s+",  // status
                 r"([^\s]+)$",    // bytes
             )).unwrap(),
+            context,
         }
     }
 }
@@ -65,7 +144,8 @@ impl LogLineParser for CommonLogLineParser {
                 let remote_host = parse_text_value(&matches, 1, line)?;
                 let rfc931 = parse_text_value(&matches, 2, line)?;
                 let username = parse_text_value(&matches, 3, line)?;
-                let timestamp = parse_timestamp(&matches, 4, line, COMMON_LOG_TIMESTAMP)?;
+                let timestamp =
+                    parse_timestamp(&matches, 4, line, COMMON_LOG_TIMESTAMP, &self.context)?;
                 let request = parse_text_value(&matches, 5, line)?;
                 let method = parse_text_value(&matches, 6, line)?;
                 let path = parse_text_value(&matches, 7, line)?;
@@ -94,17 +174,74 @@ fn parse_timestamp(
     index: usize,
     line: &str,
     format: &str,
+    context: &Context,
 ) -> RedeyeResult<LogFieldValue> {
     let field_match = matches
         .get(index)
         .ok_or_else(|| RedeyeError::ParseError(line.to_string()))?;
+    let text = field_match.as_str();
+
+    // The common case: the timestamp carries its own offset and date.
+    if let Ok(dt) = DateTime::parse_from_str(text, format) {
+        return Ok(LogFieldValue::Timestamp(dt));
+    }
+
+    let offset = context
+        .default_timezone
+        .unwrap_or_else(|| FixedOffset::east(0));
+
+    // No offset in the input - strip the offset specifier from the format
+    // and parse what's left as a naive datetime, applying the context
+    // timezone (UTC if none was configured).
+    let naive_format = strip_offset(format);
+    if let Ok(naive) = NaiveDateTime::parse_from_str(text, &naive_format) {
+        return resolve_local(offset, naive, line);
+    }
+
+    // No date in the input either - drop the date specifiers too and
+    // combine the parsed time with the context's override date, or
+    // today's date if none was given.
+    let time_format = time_only(&naive_format);
+    if let Ok(time) = NaiveTime::parse_from_str(text, time_format) {
+        let date = context
+            .assume_date
+            .unwrap_or_else(|| Local::now().naive_local().date());
+        return resolve_local(offset, date.and_time(time), line);
+    }
 
     Ok(LogFieldValue::Timestamp(DateTime::parse_from_str(
-        field_match.as_str(),
-        format,
+        text, format,
     )?))
 }
 
+/// Strips a trailing `%z` (and any space before it) from a timestamp
+/// format, leaving a format that matches the same text minus its offset.
+fn strip_offset(format: &str) -> String {
+    format.trim_end_matches("%z").trim_end_matches(' ').to_string()
+}
+
+/// Reduces an offset-less timestamp format down to just its time portion,
+/// assuming the Apache convention of `date:time` (e.g. `%d/%b/%Y:%T`).
+/// Formats with no `:` separator are assumed to be time-only already.
+fn time_only(naive_format: &str) -> &str {
+    match naive_format.rfind(':') {
+        Some(idx) => &naive_format[idx + 1..],
+        None => naive_format,
+    }
+}
+
+fn resolve_local(
+    offset: FixedOffset,
+    naive: NaiveDateTime,
+    line: &str,
+) -> RedeyeResult<LogFieldValue> {
+    offset
+        .from_local_datetime(&naive)
+        .single()
+        .map(LogFieldValue::Timestamp)
+        .ok_or_else(|| RedeyeError::ParseError(line.to_string()))
+}
+
 fn parse_text_value(matches: &Captures, index: usize, line: &str) -> RedeyeResult<LogFieldValue> {
     let field_match = matches
         .get(index)
@@ -126,13 +263,349 @@ fn parse_int_value(matches: &Captures, index: usize, line: &str) -> RedeyeResult
     Ok(LogFieldValue::Int(val))
 }
 
+pub struct CombinedLogLineParser {
+    regex: Regex,
+    context: Context,
+}
+
+impl CombinedLogLineParser {
+    pub fn new(context: Context) -> Self {
+        Self {
+            regex: Regex::new(concat!(
+                r"^([^\s]+)\s+", // host
+                r"([^\s]+)\s+",  // rfc931
+                r"([^\s]+)\s+",  // username
+                r"\[(.+)\]\s+",  // timestamp
+                "\"(",           // open " and HTTP request
+                r"([^\s]+)\s",   // method
+                r"([^\s]+)\s",   // path
+                r"([^\s]+)",     // protocol
+                ")\"\\s+",       // close " and HTTP request
+                r"([^\s]+)\s+",  // status
+                r"([^\s]+)\s+",  // bytes
+                "\"(.*)\"\\s+",  // referer
+                "\"(.*)\"$",     // user agent
+            )).unwrap(),
+            context,
+        }
+    }
+}
+
+impl LogLineParser for CombinedLogLineParser {
+    fn parse(&self, line: &str) -> RedeyeResult<LogEvent> {
+        self.regex
+            .captures(line.trim())
+            .ok_or_else(|| RedeyeError::ParseError(line.to_string()))
+            .and_then(|matches| {
+                let mut map = HashMap::with_capacity(matches.len());
+                let remote_host = parse_text_value(&matches, 1, line)?;
+                let rfc931 = parse_text_value(&matches, 2, line)?;
+                let username = parse_text_value(&matches, 3, line)?;
+                let timestamp =
+                    parse_timestamp(&matches, 4, line, COMMON_LOG_TIMESTAMP, &self.context)?;
+                let request = parse_text_value(&matches, 5, line)?;
+                let method = parse_text_value(&matches, 6, line)?;
+                let path = parse_text_value(&matches, 7, line)?;
+                let protocol = parse_text_value(&matches, 8, line)?;
+                let status = parse_int_value(&matches, 9, line)?;
+                let bytes = parse_int_value(&matches, 10, line)?;
+                let referer = parse_text_value(&matches, 11, line)?;
+                let user_agent = parse_text_value(&matches, 12, line)?;
+
+                map.insert("remote_host".to_string(), remote_host);
+                map.insert("some_nonsense".to_string(), rfc931);
+                map.insert("username".to_string(), username);
+                map.insert("@timestamp".to_string(), timestamp);
+                map.insert("request_url".to_string(), request);
+                map.insert("method".to_string(), method);
+                map.insert("request_uri".to_string(), path);
+                map.insert("protocol".to_string(), protocol);
+                map.insert("status_code".to_string(), status);
+                map.insert("bytes".to_string(), bytes);
+                map.insert("referer".to_string(), referer);
+                map.insert("user_agent".to_string(), user_agent);
+
+                Ok(LogEvent::from(map))
+            })
+    }
+}
+
+/// A single piece of a tokenized `LogFormat` spec: either literal text
+/// that must be matched verbatim, or a directive that captures a field.
+/// `quoted` records whether the directive was wrapped in literal double
+/// quotes in the spec (e.g. `"%r"`), in which case the surrounding quotes
+/// are matched by the directive's own pattern, just outside its capture
+/// group, instead of treated as literal text.
+enum FormatToken {
+    Literal(String),
+    Directive(FormatDirective, bool),
+}
+
+/// A known Apache `LogFormat` directive, along with the field name and
+/// `LogFieldValue` variant it should be captured as. `Header` carries the
+/// lowercased header name from a `%{Name}i` directive.
+#[derive(Clone)]
+enum FormatDirective {
+    RemoteHost,
+    Ident,
+    Username,
+    Timestamp,
+    Request,
+    Status,
+    Bytes,
+    Header(String),
+}
+
+/// Parses Apache log entries using a format compiled at runtime from a
+/// `LogFormat` spec, e.g. `%h %l %u %t "%r" %>s %b`.
+pub struct FormatLogLineParser {
+    regex: Regex,
+    fields: Vec<(String, FormatDirective)>,
+    context: Context,
+}
+
+impl FormatLogLineParser {
+    pub fn new(format: &str, context: Context) -> RedeyeResult<Self> {
+        let tokens = tokenize_format(format)?;
+        let mut pattern = String::from("^");
+        let mut fields = Vec::new();
+
+        for token in tokens {
+            match token {
+                FormatToken::Literal(text) => pattern.push_str(&regex::escape(&text)),
+                FormatToken::Directive(directive, quoted) => {
+                    let name = match directive {
+                        FormatDirective::RemoteHost => "remote_host".to_string(),
+                        FormatDirective::Ident => "ident".to_string(),
+                        FormatDirective::Username => "username".to_string(),
+                        FormatDirective::Timestamp => "@timestamp".to_string(),
+                        FormatDirective::Request => "request".to_string(),
+                        FormatDirective::Status => "status_code".to_string(),
+                        FormatDirective::Bytes => "bytes".to_string(),
+                        FormatDirective::Header(ref header_name) => header_name.clone(),
+                    };
+
+                    if let FormatDirective::Timestamp = directive {
+                        pattern.push_str(r"\[(.+?)\]");
+                    } else if quoted {
+                        pattern.push_str("\"([^\"]*)\"");
+                    } else {
+                        pattern.push_str(r"(\S+)");
+                    }
+
+                    fields.push((name, directive));
+                }
+            }
+        }
+
+        pattern.push('$');
+
+        Ok(Self {
+            regex: Regex::new(&pattern).map_err(|_| RedeyeError::ParseError(format.to_string()))?,
+            fields,
+            context,
+        })
+    }
+}
+
+impl LogLineParser for FormatLogLineParser {
+    fn parse(&self, line: &str) -> RedeyeResult<LogEvent> {
+        let matches = self
+            .regex
+            .captures(line.trim())
+            .ok_or_else(|| RedeyeError::ParseError(line.to_string()))?;
+
+        let mut map = HashMap::with_capacity(self.fields.len());
+
+        for (index, &(ref name, ref directive)) in self.fields.iter().enumerate() {
+            let group = index + 1;
+
+            match *directive {
+                FormatDirective::Timestamp => {
+                    map.insert(
+                        name.clone(),
+                        parse_timestamp(&matches, group, line, COMMON_LOG_TIMESTAMP, &self.context)?,
+                    );
+                }
+                FormatDirective::Status | FormatDirective::Bytes => {
+                    let field_match = matches
+                        .get(group)
+                        .ok_or_else(|| RedeyeError::ParseError(line.to_string()))?;
+
+                    let value = if field_match.as_str() == "-" {
+                        LogFieldValue::Int(0)
+                    } else {
+                        LogFieldValue::Int(
+                            field_match
+                                .as_str()
+                                .parse::<u64>()
+                                .map_err(|_| RedeyeError::ParseError(line.to_string()))?,
+                        )
+                    };
+
+                    map.insert(name.clone(), value);
+                }
+                FormatDirective::Request => {
+                    let request = parse_text_value(&matches, group, line)?;
+                    if let LogFieldValue::Text(ref text) = request {
+                        let mut parts = text.splitn(3, ' ');
+                        if let Some(method) = parts.next() {
+                            map.insert(
+                                "method".to_string(),
+                                LogFieldValue::Text(method.to_string()),
+                            );
+                        }
+                        if let Some(uri) = parts.next() {
+                            map.insert(
+                                "request_uri".to_string(),
+                                LogFieldValue::Text(uri.to_string()),
+                            );
+                        }
+                        if let Some(protocol) = parts.next() {
+                            map.insert(
+                                "protocol".to_string(),
+                                LogFieldValue::Text(protocol.to_string()),
+                            );
+                        }
+                    }
+                    map.insert(name.clone(), request);
+                }
+                FormatDirective::Header(_) => {
+                    map.insert(name.clone(), parse_text_value(&matches, group, line)?);
+                }
+                FormatDirective::RemoteHost | FormatDirective::Ident | FormatDirective::Username => {
+                    map.insert(name.clone(), parse_text_value(&matches, group, line)?);
+                }
+            }
+        }
+
+        Ok(LogEvent::from(map))
+    }
+}
+
+fn tokenize_format(format: &str) -> RedeyeResult<Vec<FormatToken>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+
+        // Skip an optional "%>s"-style status modifier.
+        if chars.peek() == Some(&'>') {
+            chars.next();
+        }
+
+        let directive = match chars.next() {
+            Some('h') => FormatDirective::RemoteHost,
+            Some('l') => FormatDirective::Ident,
+            Some('u') => FormatDirective::Username,
+            Some('t') => FormatDirective::Timestamp,
+            Some('r') => FormatDirective::Request,
+            Some('s') => FormatDirective::Status,
+            Some('b') => FormatDirective::Bytes,
+            Some('{') => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(RedeyeError::ParseError(format.to_string())),
+                    }
+                }
+
+                match chars.next() {
+                    Some('i') => {}
+                    _ => return Err(RedeyeError::ParseError(format.to_string())),
+                }
+
+                FormatDirective::Header(name.to_lowercase())
+            }
+            _ => return Err(RedeyeError::ParseError(format.to_string())),
+        };
+
+        // A directive immediately wrapped in literal double quotes, e.g.
+        // `"%r"`, has its quotes folded into the directive's pattern (just
+        // outside the capture group) rather than matched as literal text.
+        let quoted = literal.ends_with('"') && chars.peek() == Some(&'"');
+        if quoted {
+            literal.pop();
+        }
+
+        if !literal.is_empty() {
+            tokens.push(FormatToken::Literal(literal.clone()));
+            literal.clear();
+        }
+
+        tokens.push(FormatToken::Directive(directive, quoted));
+
+        if quoted {
+            chars.next();
+        }
+    }
+
+    if !literal.is_empty() {
+        tokens.push(FormatToken::Literal(literal));
+    }
+
+    Ok(tokens)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{CommonLogLineParser, LogLineParser};
+    use super::{
+        CombinedLogLineParser, CommonLogLineParser, Context, FormatLogLineParser, LogFieldValue,
+        LogLineParser,
+    };
+    use chrono::FixedOffset;
 
     #[test]
     fn test_common_log_line_parser() {
-        let parser = CommonLogLineParser::new();
+        let parser = CommonLogLineParser::new(Context::new());
         println!("Res: {:?}", parser.parse("125.125.125.125 - dsmith [10/Oct/1999:21:15:05 +0500] \"GET /index.html HTTP/1.0\" 200 1043"));
     }
+
+    #[test]
+    fn test_common_log_line_parser_applies_default_timezone() {
+        let context = Context::new().with_default_timezone(FixedOffset::east(5 * 3600));
+        let parser = CommonLogLineParser::new(context);
+        let event = parser
+            .parse("125.125.125.125 - dsmith [10/Oct/1999:21:15:05] \"GET /index.html HTTP/1.0\" 200 1043")
+            .unwrap();
+
+        match event.get("@timestamp") {
+            Some(LogFieldValue::Timestamp(ts)) => {
+                assert_eq!(ts.to_rfc3339(), "1999-10-10T21:15:05+05:00")
+            }
+            other => panic!("expected a timestamp field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_combined_log_line_parser() {
+        let parser = CombinedLogLineParser::new(Context::new());
+        println!(
+            "Res: {:?}",
+            parser.parse(
+                "125.125.125.125 - dsmith [10/Oct/1999:21:15:05 +0500] \"GET /index.html HTTP/1.0\" 200 1043 \"http://example.com/\" \"curl/7.54.0\""
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_log_line_parser() {
+        let parser = FormatLogLineParser::new(
+            "%h %l %u %t \"%r\" %>s %b \"%{Referer}i\" \"%{User-Agent}i\"",
+            Context::new(),
+        ).unwrap();
+        println!(
+            "Res: {:?}",
+            parser.parse(
+                "125.125.125.125 - dsmith [10/Oct/1999:21:15:05 +0500] \"GET /index.html HTTP/1.0\" 200 1043 \"http://example.com/\" \"curl/7.54.0\""
+            )
+        );
+    }
 }
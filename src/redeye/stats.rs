@@ -0,0 +1,109 @@
+//
+//
+//
+
+use parser::LogEvent;
+use std::collections::HashMap;
+
+/// How many entries to show per ranked dimension in a rendered report.
+pub const DEFAULT_TOP_N: usize = 10;
+
+const STATUS_BUCKETS: &[&str] = &["2xx", "3xx", "4xx", "5xx", "other"];
+
+/// Accumulates frequency statistics over a stream of `LogEvent`s for the
+/// `--summary` analytics mode, and renders them as a report once the
+/// stream ends.
+#[derive(Debug, Default)]
+pub struct SummaryStats {
+    event_count: u64,
+    total_bytes: u64,
+    status_buckets: HashMap<&'static str, u64>,
+    request_uri_counts: HashMap<String, u64>,
+    remote_host_counts: HashMap<String, u64>,
+}
+
+impl SummaryStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold a single event into the running totals.
+    pub fn record(&mut self, event: &LogEvent) {
+        self.event_count += 1;
+
+        if let Some(uri) = event.get_text("request_uri") {
+            *self
+                .request_uri_counts
+                .entry(uri.to_string())
+                .or_insert(0) += 1;
+        }
+
+        if let Some(host) = event.get_text("remote_host") {
+            *self
+                .remote_host_counts
+                .entry(host.to_string())
+                .or_insert(0) += 1;
+        }
+
+        if let Some(status) = event.get_int("status_code") {
+            *self.status_buckets.entry(status_bucket(status)).or_insert(0) += 1;
+        }
+
+        if let Some(bytes) = event.get_int("bytes") {
+            self.total_bytes += bytes;
+        }
+    }
+
+    /// Render the accumulated statistics as a human-readable report,
+    /// keeping the top `top_n` entries of each ranked dimension.
+    pub fn render(&self, top_n: usize) -> String {
+        let mean_bytes = if self.event_count > 0 {
+            self.total_bytes as f64 / self.event_count as f64
+        } else {
+            0.0
+        };
+
+        let mut report = format!("Events processed: {}\n", self.event_count);
+
+        report.push_str("\nStatus code distribution:\n");
+        for bucket in STATUS_BUCKETS {
+            let count = *self.status_buckets.get(bucket).unwrap_or(&0);
+            report.push_str(&format!("  {:<5} {}\n", bucket, count));
+        }
+
+        report.push_str(&format!(
+            "\nBytes: total={} mean={:.2}\n",
+            self.total_bytes, mean_bytes
+        ));
+
+        report.push_str(&format!("\nTop {} request URIs:\n", top_n));
+        report.push_str(&render_top_n(&self.request_uri_counts, top_n));
+
+        report.push_str(&format!("\nTop {} remote hosts:\n", top_n));
+        report.push_str(&render_top_n(&self.remote_host_counts, top_n));
+
+        report
+    }
+}
+
+fn status_bucket(status: u64) -> &'static str {
+    match status / 100 {
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        5 => "5xx",
+        _ => "other",
+    }
+}
+
+fn render_top_n(counts: &HashMap<String, u64>, top_n: usize) -> String {
+    let mut entries: Vec<(&str, u64)> = counts.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut report = String::new();
+    for (key, count) in entries.into_iter().take(top_n) {
+        report.push_str(&format!("  {:>8}  {}\n", count, key));
+    }
+
+    report
+}
@@ -0,0 +1,45 @@
+//
+//
+//
+
+use chrono;
+use serde_json;
+use std::io;
+
+pub type RedeyeResult<T> = Result<T, RedeyeError>;
+
+#[derive(Debug, Fail)]
+pub enum RedeyeError {
+    #[fail(display = "{}", _0)]
+    IoError(#[cause] io::Error),
+
+    #[fail(display = "{}", _0)]
+    SerializationError(#[cause] serde_json::Error),
+
+    #[fail(display = "{}", _0)]
+    TimestampParseError(#[cause] chrono::ParseError),
+
+    #[fail(display = "invalid log line: {}", _0)]
+    ParseError(String),
+
+    #[fail(display = "{}", _0)]
+    EncodingError(String),
+}
+
+impl From<io::Error> for RedeyeError {
+    fn from(err: io::Error) -> Self {
+        RedeyeError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for RedeyeError {
+    fn from(err: serde_json::Error) -> Self {
+        RedeyeError::SerializationError(err)
+    }
+}
+
+impl From<chrono::ParseError> for RedeyeError {
+    fn from(err: chrono::ParseError) -> Self {
+        RedeyeError::TimestampParseError(err)
+    }
+}